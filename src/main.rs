@@ -1,18 +1,73 @@
 use anyhow::{Result, Ok, Error, bail};
 use fs_extra::dir::CopyOptions;
+use serde::{Deserialize, Serialize};
 
 use std::{
-    path::{PathBuf, Path}, 
-    fs::{OpenOptions, remove_dir_all, create_dir, self}, 
-    io::{BufReader, BufRead, Write}, 
-    process::{Command, ChildStdout, Stdio, ChildStdin}, 
+    path::{PathBuf, Path},
+    fs::{OpenOptions, remove_dir_all, create_dir, self},
+    io::{BufReader, BufRead, Write},
+    process::{Command, ChildStdout, Stdio, ChildStdin},
+    collections::{VecDeque, HashSet, HashMap},
     time::Duration};
 
-use clap::Parser;
+use clap::{Parser, Subcommand, Args as ClapArgs};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    pub command: AppCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum AppCommand {
+    /// Download every enabled mod from the manifest (and its dependencies) that isn't already installed.
+    Sync(SyncArgs),
+    /// Re-download only the mods whose Workshop content has changed since it was last installed.
+    Update(UpdateArgs),
+    /// Redownload every mod in the manifest from scratch, replacing whatever is installed.
+    /// Pinned mods that are already installed are kept as they are.
+    Clean(SyncArgs),
+    /// Remove a single installed mod, and forget it so `update` stops tracking it.
+    Remove(RemoveArgs),
+    /// Remove any installed mod that's no longer declared in the manifest or tracked as a
+    /// resolved dependency, so `mods_dir` stops carrying stray mods RimWorld would still load.
+    Prune(PruneArgs),
+}
+
+#[derive(ClapArgs, Debug)]
+struct SyncArgs {
+    /// Name to the rimworld mods folder
+    /// Example: .wine/drive_c/Games/RimWorld/Mods/
+    #[arg(short, long)]
+    pub mods_dir: PathBuf,
+
+    /// The steam directory where the rimworld mods will be downloaded.
+    /// Example: .local/share/Steam/steamapps/workshop/content/294100/
+    #[arg(short, long)]
+    pub steam_dir: PathBuf,
+
+    /// Path to RimWorld's ModsConfig.xml. When given, its <activeMods> block is rewritten
+    /// after downloading with a load order honoring each mod's loadAfter/loadBefore, with
+    /// Core and the official expansions kept pinned first.
+    #[arg(long)]
+    pub modsconfig_path: Option<PathBuf>,
+
+    /// Download into steam_dir and copy into mods_dir afterwards, instead of pointing
+    /// SteamCMD's force_install_dir straight at mods_dir. Use this if your SteamCMD version
+    /// ignores force_install_dir for workshop content.
+    #[arg(long)]
+    pub copy_after_download: bool,
+
+    /// How many `workshop_download_item` commands to queue into SteamCMD at once, instead
+    /// of paying its per-command latency one mod at a time. SteamCMD is known to choke on
+    /// very large queues, so keep this modest for big mod lists.
+    #[arg(long, default_value_t = 5)]
+    pub batch_size: usize,
+}
+
+#[derive(ClapArgs, Debug)]
+struct UpdateArgs {
     /// Name to the rimworld mods folder
     /// Example: .wine/drive_c/Games/RimWorld/Mods/
     #[arg(short, long)]
@@ -23,9 +78,40 @@ struct Args {
     #[arg(short, long)]
     pub steam_dir: PathBuf,
 
-    /// Redownload of all mods, even if they already exist
+    /// Download into steam_dir and copy into mods_dir afterwards, instead of pointing
+    /// SteamCMD's force_install_dir straight at mods_dir. Use this if your SteamCMD version
+    /// ignores force_install_dir for workshop content.
+    #[arg(long)]
+    pub copy_after_download: bool,
+
+    /// How many `workshop_download_item` commands to queue into SteamCMD at once, instead
+    /// of paying its per-command latency one mod at a time. SteamCMD is known to choke on
+    /// very large queues, so keep this modest for big mod lists.
+    #[arg(long, default_value_t = 5)]
+    pub batch_size: usize,
+}
+
+#[derive(ClapArgs, Debug)]
+struct RemoveArgs {
+    /// Name to the rimworld mods folder
+    /// Example: .wine/drive_c/Games/RimWorld/Mods/
+    #[arg(short, long)]
+    pub mods_dir: PathBuf,
+
+    /// The workshop id of the mod to remove.
+    pub id: i64,
+}
+
+#[derive(ClapArgs, Debug)]
+struct PruneArgs {
+    /// Name to the rimworld mods folder
+    /// Example: .wine/drive_c/Games/RimWorld/Mods/
     #[arg(short, long)]
-    pub clean: bool
+    pub mods_dir: PathBuf,
+
+    /// Skip the confirmation prompt and delete the orphaned mods right away.
+    #[arg(long)]
+    pub yes: bool,
 }
 
 pub const RIMWORLD_GAME_ID: u64 = 294100;
@@ -33,27 +119,138 @@ pub const RIMWORLD_GAME_ID: u64 = 294100;
 struct RimMod {
     pub name: String,
     pub id: i64,
-    pub _url: String
+    pub _url: String,
+    /// Never redownload/replace this mod, even under the `clean` subcommand.
+    pub pinned: bool,
+    /// Mods loaded from the manifest but not enabled are skipped entirely.
+    pub enabled: bool,
+    /// Preferred position in the load order, lowest first; loadAfter/loadBefore constraints
+    /// take precedence over it. Only settable via `mods.toml`.
+    pub load_order: Option<i32>,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// A `mods.toml` manifest: a structured, commentable alternative to the legacy `mods.txt`.
+#[derive(Debug, Deserialize)]
+struct ModsManifest {
+    #[serde(rename = "mod", default)]
+    mods: Vec<ManifestMod>,
+}
 
-    if !args.mods_dir.is_dir() {
-        return Err(Error::msg("mods_dir expected to be a directory and exist!"));
-    }
+#[derive(Debug, Deserialize)]
+struct ManifestMod {
+    name: String,
+    id: Option<i64>,
+    url: Option<String>,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    load_order: Option<i32>,
+}
 
-    if !args.steam_dir.is_dir() {
-        return Err(Error::msg("steam_dir expected to be a directory and exist!"));
+fn default_enabled() -> bool {
+    true
+}
+
+/// The subset of `About/About.xml`'s `<ModMetaData>` we care about.
+#[derive(Debug, Default, Deserialize)]
+struct ModMetaData {
+    #[serde(rename = "packageId", default)]
+    package_id: String,
+    #[serde(rename = "modDependencies", default)]
+    mod_dependencies: ModDependencyList,
+    #[serde(rename = "loadAfter", default)]
+    load_after: StringList,
+    #[serde(rename = "loadBefore", default)]
+    load_before: StringList,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ModDependencyList {
+    #[serde(rename = "li", default)]
+    li: Vec<ModDependency>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModDependency {
+    #[serde(rename = "steamWorkshopUrl", default)]
+    steam_workshop_url: Option<String>,
+}
+
+/// A plain `<li>...</li>` list, shared by `loadAfter`/`loadBefore` and `<activeMods>`.
+#[derive(Debug, Default, Deserialize)]
+struct StringList {
+    #[serde(rename = "li", default)]
+    li: Vec<String>,
+}
+
+/// The subset of `ModsConfig.xml` we need before rewriting `<activeMods>`.
+#[derive(Debug, Default, Deserialize)]
+struct ModsConfigData {
+    #[serde(rename = "activeMods", default)]
+    active_mods: StringList,
+}
+
+const OFFICIAL_PACKAGE_ID_PREFIX: &str = "ludeon.";
+
+/// The local record of what's installed, keyed by workshop id.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct InstallDb {
+    #[serde(default)]
+    mods: HashMap<i64, InstallRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InstallRecord {
+    time_updated: i64,
+    /// Mirrors the manifest's `pinned` flag, so `update` can skip this mod without needing
+    /// to re-read the manifest.
+    #[serde(default)]
+    pinned: bool,
+}
+
+fn install_db_path(mods_dir: &Path) -> PathBuf {
+    mods_dir.join("rimmods-state.json")
+}
+
+fn load_install_db(mods_dir: &Path) -> Result<InstallDb> {
+    let path = install_db_path(mods_dir);
+
+    if !path.is_file() {
+        return Ok(InstallDb::default());
     }
 
-    let modlist_path = args.mods_dir.join("mods.txt");
+    let contents = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+fn save_install_db(mods_dir: &Path, db: &InstallDb) -> Result<()> {
+    let contents = serde_json::to_string_pretty(db)?;
+    fs::write(install_db_path(mods_dir), contents)?;
+    Ok(())
+}
+
+/// Records `id`'s current Workshop `time_updated` in `db`, if the Steam Web API can be reached.
+fn record_install(db: &mut InstallDb, client: &reqwest::blocking::Client, id: i64, pinned: bool) {
+    if let Result::Ok(Some(time_updated)) = fetch_time_updated(client, id) {
+        db.mods.insert(id, InstallRecord { time_updated, pinned });
+    }
+}
 
-    if !modlist_path.is_file() {
-        return Err(Error::msg(format!("Error! mods.txt file not found in {:?}", args.mods_dir)));
+fn main() -> Result<()> {
+    let args = Args::parse();
 
+    match args.command {
+        AppCommand::Sync(sync_args) => run_sync(&sync_args, false),
+        AppCommand::Clean(sync_args) => run_sync(&sync_args, true),
+        AppCommand::Update(update_args) => run_update(&update_args),
+        AppCommand::Remove(remove_args) => run_remove(&remove_args),
+        AppCommand::Prune(prune_args) => run_prune(&prune_args),
     }
+}
 
+/// Spawns SteamCMD and waits for the anonymous login to go through.
+fn spawn_steamcmd() -> Result<(ChildStdin, BufReader<ChildStdout>)> {
     println!("Spawning SteamCMD...");
 
     let steamcmd = Command::new("steamcmd")
@@ -78,116 +275,744 @@ fn main() -> Result<()> {
             println!("-----------------------------");
             println!("Logged into SteamCMD correctly");
             break;
-        }   
+        }
+    }
+
+    Ok((steam_stdin, steam_stdout))
+}
+
+/// Points SteamCMD's downloads at `mods_dir`/`steam_dir` and returns where they land.
+fn resolve_download_root(mods_dir: &Path, steam_dir: &Path, copy_after_download: bool, steam_stdin: &mut ChildStdin) -> Result<PathBuf> {
+    if copy_after_download {
+        return Ok(steam_dir.to_path_buf());
     }
 
+    println!("Pointing SteamCMD's force_install_dir at {:?}...", mods_dir);
+    writeln!(steam_stdin, "force_install_dir {}", mods_dir.display())?;
+    Ok(mods_dir.join("steamapps").join("workshop").join("content").join(RIMWORLD_GAME_ID.to_string()))
+}
+
+/// Downloads `rimmods` in one SteamCMD session, batched to `batch_size` at a time, making
+/// up to 3 attempts per id (the first try plus up to 2 retries of the failed ids).
+fn download_batch(steam_stdin: &mut ChildStdin, steam_stdout: &mut BufReader<ChildStdout>, rimmods: &[&RimMod], batch_size: usize) -> Result<HashMap<i64, bool>> {
+    let mut succeeded: HashSet<i64> = HashSet::new();
+    let mut pending: Vec<&RimMod> = rimmods.to_vec();
+    let batch_size = batch_size.max(1);
+
+    for attempt in 0..3 {
+        if pending.is_empty() {
+            break;
+        }
+
+        if attempt > 0 {
+            println!("Retrying {} failed mods (attempt {})...", pending.len(), attempt + 1);
+        }
+
+        for chunk in pending.clone().chunks(batch_size) {
+            println!("Queuing {} mods with SteamCMD...", chunk.len());
+
+            for rimmod in chunk {
+                writeln!(steam_stdin, "workshop_download_item {} {}", RIMWORLD_GAME_ID, rimmod.id)?;
+            }
+
+            let mut remaining: HashSet<i64> = chunk.iter().map(|rimmod| rimmod.id).collect();
+
+            while !remaining.is_empty() {
+                let mut line = String::new();
+                steam_stdout.read_line(&mut line)?;
+                let line = line.replace('\n', "");
+
+                println!("Steam -> {}", line);
+
+                let done_id = remaining.iter().copied().find(|&id| {
+                    line.starts_with(&format!("Success. Downloaded item {id} to"))
+                        || line.starts_with(&format!("ERROR! Download item {id} failed"))
+                });
+
+                if let Some(id) = done_id {
+                    if line.starts_with("Success.") {
+                        succeeded.insert(id);
+                    }
+                    remaining.remove(&id);
+                }
+            }
+        }
+
+        pending.retain(|rimmod| !succeeded.contains(&rimmod.id));
+    }
+
+    Ok(rimmods.iter().map(|rimmod| (rimmod.id, succeeded.contains(&rimmod.id))).collect())
+}
+
+fn run_sync(args: &SyncArgs, force_clean: bool) -> Result<()> {
+    if !args.mods_dir.is_dir() {
+        return Err(Error::msg("mods_dir expected to be a directory and exist!"));
+    }
+
+    if !args.steam_dir.is_dir() {
+        return Err(Error::msg("steam_dir expected to be a directory and exist!"));
+    }
+
+    let toml_modlist_path = args.mods_dir.join("mods.toml");
+    let txt_modlist_path = args.mods_dir.join("mods.txt");
+
+    let modlist_path = if toml_modlist_path.is_file() {
+        toml_modlist_path
+    } else if txt_modlist_path.is_file() {
+        txt_modlist_path
+    } else {
+        return Err(Error::msg(format!("Error! Neither mods.toml nor mods.txt found in {:?}", args.mods_dir)));
+    };
+
+    let (mut steam_stdin, mut steam_stdout) = spawn_steamcmd()?;
+
+    let download_root = resolve_download_root(&args.mods_dir, &args.steam_dir, args.copy_after_download, &mut steam_stdin)?;
+
     println!("Loading mods from {modlist_path:?}..");
 
     let mods = load_mods(&modlist_path)?;
 
     println!("Found {} mods", {mods.len()});
 
-    for rimmod in mods {
-        let mod_path = args.mods_dir.join(format!("{}", rimmod.id));
-        let steam_path = args.steam_dir.join(format!("{}", rimmod.id));
+    let client = http_client()?;
+    let mut db = load_install_db(&args.mods_dir)?;
 
-        if mod_path.is_dir() {
-            if args.clean {
-                println!("Removing {:?} from rimworld folder (clean)", mod_path);
-                remove_dir_all(&mod_path)?;
-            } else if fs::read_dir(&mod_path)?.count() > 0 {
-                println!("Mod {} ({}) already exists. Skipping...", rimmod.name, rimmod.id);
+    let mut visited: HashSet<i64> = mods.iter().map(|rimmod| rimmod.id).collect();
+    let mut disabled: HashSet<i64> = HashSet::new();
+    let load_orders: HashMap<i64, i32> = mods.iter()
+        .filter_map(|rimmod| rimmod.load_order.map(|load_order| (rimmod.id, load_order)))
+        .collect();
+    let mut queue: VecDeque<RimMod> = mods.into_iter().collect();
+
+    // Dependencies are only discovered after a mod is downloaded and its About.xml is
+    // read, so we work in rounds: drain the queue, resolving whatever's already installed
+    // immediately and collecting the rest, then download that round's batch in one
+    // SteamCMD session before looping back for any dependencies it turned up.
+    while !queue.is_empty() {
+        let mut to_download = Vec::new();
+
+        while let Some(rimmod) = queue.pop_front() {
+            if !rimmod.enabled {
+                println!("Mod {} ({}) is disabled. Skipping...", rimmod.name, rimmod.id);
+                disabled.insert(rimmod.id);
                 continue;
             }
-        }
 
-        if steam_path.is_dir() {
-            if args.clean {
-                println!("Removing {:?} from steam folder (clean)", steam_path);
-                remove_dir_all(&steam_path)?;
-            } else if fs::read_dir(&steam_path)?.count() > 0 {
-                println!("Mod {} ({}) already downloaded. Moving...", rimmod.name, rimmod.id);
-                if !mod_path.is_dir() {
-                    create_dir(&mod_path)?;
+            let mod_path = args.mods_dir.join(format!("{}", rimmod.id));
+            let download_path = download_root.join(format!("{}", rimmod.id));
+
+            if mod_path.is_dir() {
+                if force_clean && !rimmod.pinned {
+                    println!("Removing {:?} from rimworld folder (clean)", mod_path);
+                    remove_dir_all(&mod_path)?;
+                } else if fs::read_dir(&mod_path)?.count() > 0 {
+                    println!("Mod {} ({}) already exists. Skipping...", rimmod.name, rimmod.id);
+                    queue_dependencies(&mod_path, &mut queue, &mut visited)?;
+                    // Keep the stored time_updated of a tracked mod, since nothing was redownloaded.
+                    if let Some(record) = db.mods.get_mut(&rimmod.id) {
+                        record.pinned = rimmod.pinned;
+                    } else {
+                        record_install(&mut db, &client, rimmod.id, rimmod.pinned);
+                    }
+                    continue;
                 }
-                fs_extra::copy_items(&[steam_path], mod_path, &CopyOptions::new())?;
-                continue;
             }
+
+            if download_path.is_dir() {
+                if force_clean && !rimmod.pinned {
+                    println!("Removing {:?} from download folder (clean)", download_path);
+                    remove_dir_all(&download_path)?;
+                } else if fs::read_dir(&download_path)?.count() > 0 {
+                    println!("Mod {} ({}) already downloaded. Installing...", rimmod.name, rimmod.id);
+                    install_download(&download_path, &mod_path, args.copy_after_download)?;
+                    queue_dependencies(&mod_path, &mut queue, &mut visited)?;
+                    record_install(&mut db, &client, rimmod.id, rimmod.pinned);
+                    continue;
+                }
+            }
+
+            to_download.push(rimmod);
+        }
+
+        if to_download.is_empty() {
+            break;
         }
 
-        println!("Downloading {} ({})...", rimmod.name, rimmod.id);
+        println!("Downloading {} mods...", to_download.len());
 
-        let mut success = false;
-        for i in 0..3 {
-            match steamcmd_download(&mut steam_stdin, &mut steam_stdout, &rimmod) {
-                Err(_) => {
-                    println!("Mod download failed, retrying ({})", i);
-                    continue;
-                },
-                _ => {
-                    success = true;
+        let results = download_batch(&mut steam_stdin, &mut steam_stdout, &to_download.iter().collect::<Vec<_>>(), args.batch_size)?;
+
+        for rimmod in to_download {
+            if !results.get(&rimmod.id).copied().unwrap_or(false) {
+                bail!("Error downloading mod {} ({})", rimmod.name, rimmod.id);
+            }
+
+            let mod_path = args.mods_dir.join(format!("{}", rimmod.id));
+            let download_path = download_root.join(format!("{}", rimmod.id));
+
+            for i in 0..10 {
+                std::thread::sleep(Duration::from_millis(i * 250));
+
+                if download_path.is_dir() {
                     break;
-                },
-            };
+                }
+            }
+
+            println!("Downloaded {} ({})", rimmod.name, rimmod.id);
+
+            install_download(&download_path, &mod_path, args.copy_after_download)?;
+            queue_dependencies(&mod_path, &mut queue, &mut visited)?;
+            record_install(&mut db, &client, rimmod.id, rimmod.pinned);
         }
+    }
+
+    save_install_db(&args.mods_dir, &db)?;
+
+    if let Some(modsconfig_path) = &args.modsconfig_path {
+        let active_ids: HashSet<i64> = visited.difference(&disabled).copied().collect();
+        write_modsconfig(modsconfig_path, &args.mods_dir, &active_ids, &load_orders)?;
+    }
+
+    println!("All mods checked out. Bye!");
 
-        if !success {
-            bail!("Error downloading mod {}", rimmod.name);
+    Ok(())
+}
+
+/// Redownloads only the tracked mods whose Workshop `time_updated` changed, skipping
+/// pinned ones.
+fn run_update(args: &UpdateArgs) -> Result<()> {
+    if !args.mods_dir.is_dir() {
+        return Err(Error::msg("mods_dir expected to be a directory and exist!"));
+    }
+
+    if !args.steam_dir.is_dir() {
+        return Err(Error::msg("steam_dir expected to be a directory and exist!"));
+    }
+
+    let mut db = load_install_db(&args.mods_dir)?;
+
+    if db.mods.is_empty() {
+        println!("No tracked mods found in {:?}. Run `sync` first.", args.mods_dir);
+        return Ok(());
+    }
+
+    let client = http_client()?;
+
+    println!("Checking {} tracked mods for updates...", db.mods.len());
+
+    let mut stale_ids = Vec::new();
+    for (&id, record) in &db.mods {
+        if record.pinned {
+            continue;
+        }
+
+        match fetch_time_updated(&client, id) {
+            Result::Ok(Some(time_updated)) if time_updated > record.time_updated => {
+                println!("Mod {} has an update available ({} -> {})", id, record.time_updated, time_updated);
+                stale_ids.push(id);
+            },
+            Result::Ok(_) => {},
+            Err(err) => println!("Could not check mod {} for updates: {}", id, err),
         }
+    }
+
+    if stale_ids.is_empty() {
+        println!("Everything is up to date.");
+        return Ok(());
+    }
+
+    println!("Redownloading {} stale mods...", stale_ids.len());
+
+    let (mut steam_stdin, mut steam_stdout) = spawn_steamcmd()?;
+
+    let download_root = resolve_download_root(&args.mods_dir, &args.steam_dir, args.copy_after_download, &mut steam_stdin)?;
+
+    let stale_mods: Vec<RimMod> = stale_ids.into_iter()
+        .map(|id| RimMod {
+            name: format!("tracked mod {id}"),
+            id,
+            _url: format!("https://steamcommunity.com/sharedfiles/filedetails/?id={id}"),
+            pinned: false,
+            enabled: true,
+            load_order: None,
+        })
+        .collect();
+
+    let results = download_batch(&mut steam_stdin, &mut steam_stdout, &stale_mods.iter().collect::<Vec<_>>(), args.batch_size)?;
+
+    for rimmod in stale_mods {
+        if !results.get(&rimmod.id).copied().unwrap_or(false) {
+            println!("Error downloading mod {} ({}), leaving it tracked at its old version", rimmod.name, rimmod.id);
+            continue;
+        }
+
+        let mod_path = args.mods_dir.join(format!("{}", rimmod.id));
+        let download_path = download_root.join(format!("{}", rimmod.id));
 
         for i in 0..10 {
             std::thread::sleep(Duration::from_millis(i * 250));
 
-            if steam_path.is_dir() {
+            if download_path.is_dir() {
                 break;
             }
         }
 
-        println!("Downloaded {} ({})", rimmod.name, rimmod.id);
+        // Only touch the old install once the redownload has actually succeeded, so a
+        // failed/interrupted update leaves the previous working copy in place.
+        if mod_path.is_dir() {
+            remove_dir_all(&mod_path)?;
+        }
+
+        install_download(&download_path, &mod_path, args.copy_after_download)?;
+        record_install(&mut db, &client, rimmod.id, false);
+    }
+
+    save_install_db(&args.mods_dir, &db)?;
+
+    println!("Update complete.");
+
+    Ok(())
+}
+
+/// Removes a single installed mod from `mods_dir` and stops tracking it in the install db.
+fn run_remove(args: &RemoveArgs) -> Result<()> {
+    if !args.mods_dir.is_dir() {
+        return Err(Error::msg("mods_dir expected to be a directory and exist!"));
+    }
+
+    let mod_path = args.mods_dir.join(format!("{}", args.id));
+
+    if mod_path.is_dir() {
+        println!("Removing {:?}...", mod_path);
+        remove_dir_all(&mod_path)?;
+    } else {
+        println!("Mod {} is not installed in {:?}", args.id, args.mods_dir);
+    }
+
+    let mut db = load_install_db(&args.mods_dir)?;
+    if db.mods.remove(&args.id).is_some() {
+        save_install_db(&args.mods_dir, &db)?;
+    }
+
+    println!("Removed mod {}", args.id);
+
+    Ok(())
+}
+
+/// Removes any numeric-id subdirectory of `mods_dir` that's neither declared in the manifest
+/// nor a dependency (transitively, via installed About.xml files) of a declared mod.
+fn run_prune(args: &PruneArgs) -> Result<()> {
+    if !args.mods_dir.is_dir() {
+        return Err(Error::msg("mods_dir expected to be a directory and exist!"));
+    }
+
+    let toml_modlist_path = args.mods_dir.join("mods.toml");
+    let txt_modlist_path = args.mods_dir.join("mods.txt");
+
+    let modlist_path = if toml_modlist_path.is_file() {
+        toml_modlist_path
+    } else if txt_modlist_path.is_file() {
+        txt_modlist_path
+    } else {
+        return Err(Error::msg(format!("Error! Neither mods.toml nor mods.txt found in {:?}", args.mods_dir)));
+    };
+
+    let mods = load_mods(&modlist_path)?;
+    let mut db = load_install_db(&args.mods_dir)?;
+
+    let mut keep_ids: HashSet<i64> = mods.iter().map(|rimmod| rimmod.id).collect();
+    let mut queue: VecDeque<RimMod> = mods.into_iter().collect();
+    while let Some(rimmod) = queue.pop_front() {
+        queue_dependencies(&args.mods_dir.join(format!("{}", rimmod.id)), &mut queue, &mut keep_ids)?;
+    }
+
+    let mut orphans = Vec::new();
+    for entry in fs::read_dir(&args.mods_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let Some(id) = entry.file_name().to_str().and_then(|name| name.parse::<i64>().ok()) else {
+            continue;
+        };
+
+        if !keep_ids.contains(&id) {
+            orphans.push(id);
+        }
+    }
+
+    if orphans.is_empty() {
+        println!("Nothing to prune, {:?} already matches the manifest.", args.mods_dir);
+        return Ok(());
+    }
+
+    println!("The following mods are installed but no longer in the manifest:");
+    for id in &orphans {
+        println!("  {id}");
+    }
+
+    if !args.yes {
+        print!("Remove {} mod(s) from {:?}? [y/N] ", orphans.len(), args.mods_dir);
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            println!("Aborted, nothing removed.");
+            return Ok(());
+        }
+    }
+
+    for id in orphans {
+        let mod_path = args.mods_dir.join(format!("{id}"));
+        println!("Removing {:?}...", mod_path);
+        remove_dir_all(&mod_path)?;
+        db.mods.remove(&id);
+    }
+
+    save_install_db(&args.mods_dir, &db)?;
+
+    println!("Prune complete.");
+
+    Ok(())
+}
 
+/// Installs a downloaded mod from `download_path` into `mod_path`, copying if `copy` is
+/// set, otherwise moving it.
+fn install_download(download_path: &Path, mod_path: &Path, copy: bool) -> Result<()> {
+    if copy {
         if !mod_path.is_dir() {
-            create_dir(&mod_path)?;
+            create_dir(mod_path)?;
+        }
+        fs_extra::copy_items(&[download_path], mod_path, &CopyOptions::new())?;
+    } else {
+        if mod_path.is_dir() {
+            remove_dir_all(mod_path)?;
         }
+        fs::rename(download_path, mod_path)?;
+    }
+
+    Ok(())
+}
+
+/// Reads `<mod_path>/About/About.xml`, if present, and queues any dependency not already
+/// `visited`.
+fn queue_dependencies(mod_path: &Path, queue: &mut VecDeque<RimMod>, visited: &mut HashSet<i64>) -> Result<()> {
+    let about_path = mod_path.join("About").join("About.xml");
 
-        fs_extra::copy_items(&[steam_path], mod_path, &CopyOptions::new())?;
+    if !about_path.is_file() {
+        return Ok(());
+    }
+
+    let about = parse_about_xml(&about_path)?;
+
+    for dependency in about.mod_dependencies.li {
+        let Some(url) = dependency.steam_workshop_url else {
+            continue;
+        };
+
+        let Some(id) = extract_workshop_id(&url).ok() else {
+            continue;
+        };
+
+        if visited.insert(id) {
+            println!("Queuing dependency {} found in {:?}", id, about_path);
+            queue.push_back(RimMod {
+                name: format!("dependency {id}"),
+                id,
+                _url: url,
+                pinned: false,
+                enabled: true,
+                load_order: None,
+            });
+        }
     }
 
-    println!("All mods checked out. Bye!");
-    
     Ok(())
 }
 
-fn steamcmd_download(steam_stdin: &mut ChildStdin, steam_stdout: &mut BufReader<ChildStdout>, rimmod: &RimMod) -> Result<()> {
-    println!("-----------------------------");
+fn parse_about_xml(about_path: &Path) -> Result<ModMetaData> {
+    let contents = fs::read_to_string(about_path)?;
+    Ok(quick_xml::de::from_str(&contents)?)
+}
 
-    writeln!(steam_stdin, "workshop_download_item {} {}", RIMWORLD_GAME_ID, rimmod.id)?;
-    loop {
-        let mut line = String::new();
-        steam_stdout.read_line(&mut line)?;
-        let line = line.replace('\n', "");
+/// Rewrites `modsconfig_path`'s `<activeMods>` block with Core/official expansions pinned
+/// first, followed by `installed_ids` ordered to honor `loadAfter`/`loadBefore`.
+fn write_modsconfig(modsconfig_path: &Path, mods_dir: &Path, installed_ids: &HashSet<i64>, load_orders: &HashMap<i64, i32>) -> Result<()> {
+    println!("Writing load order to {:?}...", modsconfig_path);
 
-        println!("Steam -> {}", line);
-        if line.starts_with(&format!("Success. Downloaded item {} to", rimmod.id)) {
-            println!("-----------------------------");
-            break;
+    let mut metas = Vec::new();
+    let mut load_order_overrides: HashMap<String, i32> = HashMap::new();
+    for &id in installed_ids {
+        let about_path = mods_dir.join(format!("{id}")).join("About").join("About.xml");
+        if !about_path.is_file() {
+            continue;
         }
 
-        if line.starts_with(&format!("ERROR! Download item {} failed", rimmod.id)) {
-            println!("-----------------------------");
-            bail!("Error downloading mod {} ({})", rimmod.name, rimmod.id);
+        let meta = parse_about_xml(&about_path)?;
+        if meta.package_id.is_empty() {
+            continue;
+        }
+
+        if let Some(&load_order) = load_orders.get(&id) {
+            load_order_overrides.insert(meta.package_id.to_lowercase(), load_order);
+        }
+
+        metas.push(meta);
+    }
+
+    let sorted = topo_sort_mods(metas, &load_order_overrides);
+
+    let mut active_mods = read_existing_active_mods(modsconfig_path).into_iter()
+        .filter(|package_id| package_id.to_lowercase().starts_with(OFFICIAL_PACKAGE_ID_PREFIX))
+        .collect::<Vec<String>>();
+
+    for package_id in sorted {
+        if !active_mods.iter().any(|existing| existing.eq_ignore_ascii_case(&package_id)) {
+            active_mods.push(package_id);
         }
     }
 
+    let active_mods_block = render_active_mods_block(&active_mods);
+
+    let new_contents = match fs::read_to_string(modsconfig_path) {
+        Result::Ok(existing) => splice_active_mods(&existing, &active_mods_block)
+            .ok_or_else(|| Error::msg(format!("Could not find an <activeMods> tag to replace in {:?}", modsconfig_path)))?,
+        Err(_) => format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<ModsConfigData>\n  <version>Unknown</version>\n  {}\n</ModsConfigData>\n",
+            active_mods_block
+        ),
+    };
+
+    fs::write(modsconfig_path, new_contents)?;
+
+    println!("Wrote load order for {} mods", active_mods.len());
+
     Ok(())
 }
 
+/// Reads the `packageId`s currently in `<activeMods>`, defaulting to just Core when the
+/// file doesn't exist yet (e.g. on a machine RimWorld hasn't been launched on).
+fn read_existing_active_mods(modsconfig_path: &Path) -> Vec<String> {
+    let contents = match fs::read_to_string(modsconfig_path) {
+        Result::Ok(contents) => contents,
+        Err(_) => return vec!["ludeon.rimworld".to_owned()],
+    };
+
+    match quick_xml::de::from_str::<ModsConfigData>(&contents) {
+        Result::Ok(config) => config.active_mods.li,
+        Err(_) => vec!["ludeon.rimworld".to_owned()],
+    }
+}
+
+/// Orders `metas` by their `loadAfter`/`loadBefore` constraints (Kahn's algorithm), breaking
+/// ties with `load_order_overrides` and any cycle by appending the rest unordered.
+fn topo_sort_mods(metas: Vec<ModMetaData>, load_order_overrides: &HashMap<String, i32>) -> Vec<String> {
+    let nodes: Vec<String> = metas.iter().map(|meta| meta.package_id.to_lowercase()).collect();
+    let node_set: HashSet<&str> = nodes.iter().map(|node| node.as_str()).collect();
+
+    let mut in_degree: HashMap<String, usize> = nodes.iter().map(|node| (node.clone(), 0)).collect();
+    let mut adjacency: HashMap<String, Vec<String>> = HashMap::new();
+
+    for meta in &metas {
+        let this_id = meta.package_id.to_lowercase();
+
+        for after in &meta.load_after.li {
+            let after_id = after.to_lowercase();
+            if node_set.contains(after_id.as_str()) {
+                adjacency.entry(after_id).or_default().push(this_id.clone());
+                *in_degree.get_mut(&this_id).unwrap() += 1;
+            }
+        }
+
+        for before in &meta.load_before.li {
+            let before_id = before.to_lowercase();
+            if node_set.contains(before_id.as_str()) {
+                adjacency.entry(this_id.clone()).or_default().push(before_id.clone());
+                *in_degree.get_mut(&before_id).unwrap() += 1;
+            }
+        }
+    }
+
+    let mut ready: Vec<String> = nodes.iter()
+        .filter(|node| in_degree[*node] == 0)
+        .cloned()
+        .collect();
+
+    let mut sorted = Vec::new();
+    while !ready.is_empty() {
+        ready.sort_by_key(|node| load_order_overrides.get(node).copied().unwrap_or(i32::MAX));
+        let node = ready.remove(0);
+        sorted.push(node.clone());
+
+        if let Some(neighbors) = adjacency.get(&node) {
+            for neighbor in neighbors {
+                let degree = in_degree.get_mut(neighbor).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push(neighbor.clone());
+                }
+            }
+        }
+    }
+
+    if sorted.len() != nodes.len() {
+        println!("Warning: cyclic loadAfter/loadBefore constraints detected, appending the rest unordered");
+        for node in nodes {
+            if !sorted.contains(&node) {
+                sorted.push(node);
+            }
+        }
+    }
+
+    sorted
+}
+
+fn render_active_mods_block(active_mods: &[String]) -> String {
+    let mut block = String::from("<activeMods>\n");
+    for package_id in active_mods {
+        block.push_str(&format!("    <li>{package_id}</li>\n"));
+    }
+    block.push_str("  </activeMods>");
+    block
+}
+
+/// Replaces `existing`'s `<activeMods>` tag (open/close or self-closing) with `block`,
+/// leaving the rest of the document untouched.
+fn splice_active_mods(existing: &str, block: &str) -> Option<String> {
+    if let (Some(start), Some(end)) = (existing.find("<activeMods>"), existing.find("</activeMods>")) {
+        let end = end + "</activeMods>".len();
+        return Some(format!("{}{}{}", &existing[..start], block, &existing[end..]));
+    }
+
+    let start = existing.find("<activeMods")?;
+    let close = existing[start..].find("/>")? + start + "/>".len();
+    Some(format!("{}{}{}", &existing[..start], block, &existing[close..]))
+}
+
+/// Loads the mod list, dispatching on the manifest's file extension.
 fn load_mods(list_path: &Path) -> Result<Vec<RimMod>> {
+    match list_path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => load_mods_toml(list_path),
+        _ => load_mods_txt(list_path),
+    }
+}
+
+fn extract_workshop_id(url: &str) -> Result<i64> {
+    let url_split = url.split("?id=")
+        .collect::<Vec<&str>>();
+
+    let id_str = *url_split.get(1).ok_or_else(|| Error::msg(format!("Could not find a workshop id in url {url}")))?;
+    Ok(id_str.parse::<i64>()?)
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectionDetailsEnvelope {
+    response: CollectionDetailsResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectionDetailsResponse {
+    #[serde(default)]
+    collectiondetails: Vec<CollectionDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectionDetails {
+    #[serde(default)]
+    result: i32,
+    #[serde(default)]
+    children: Vec<CollectionChild>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectionChild {
+    publishedfileid: String,
+}
+
+/// Expands `id` into its member ids if it's a Workshop collection, otherwise returns it
+/// unchanged.
+fn expand_collection(client: &reqwest::blocking::Client, id: i64) -> Vec<i64> {
+    match fetch_collection_children(client, id) {
+        Result::Ok(children) if !children.is_empty() => children,
+        _ => vec![id],
+    }
+}
+
+fn fetch_collection_children(client: &reqwest::blocking::Client, collection_id: i64) -> Result<Vec<i64>> {
+    let envelope = client
+        .post("https://api.steampowered.com/ISteamRemoteStorage/GetCollectionDetails/v1/")
+        .form(&[("collectioncount", "1"), ("publishedfileids[0]", &collection_id.to_string())])
+        .send()?
+        .json::<CollectionDetailsEnvelope>()?;
+
+    let Some(details) = envelope.response.collectiondetails.into_iter().next() else {
+        return Ok(Vec::new());
+    };
+
+    if details.result != 1 || details.children.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    details.children.into_iter()
+        .map(|child| Ok(child.publishedfileid.parse::<i64>()?))
+        .collect()
+}
+
+/// Builds the shared client used for every Steam Web API call, with a bounded timeout.
+fn http_client() -> Result<reqwest::blocking::Client> {
+    Ok(reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .build()?)
+}
+
+#[derive(Debug, Deserialize)]
+struct PublishedFileDetailsEnvelope {
+    response: PublishedFileDetailsResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct PublishedFileDetailsResponse {
+    #[serde(default)]
+    publishedfiledetails: Vec<PublishedFileDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PublishedFileDetails {
+    #[serde(default)]
+    result: i32,
+    #[serde(default)]
+    time_updated: i64,
+}
+
+/// Queries the Steam Web API for `id`'s current `time_updated` Workshop timestamp.
+fn fetch_time_updated(client: &reqwest::blocking::Client, id: i64) -> Result<Option<i64>> {
+    let envelope = client
+        .post("https://api.steampowered.com/ISteamRemoteStorage/GetPublishedFileDetails/v1/")
+        .form(&[("itemcount", "1"), ("publishedfileids[0]", &id.to_string())])
+        .send()?
+        .json::<PublishedFileDetailsEnvelope>()?;
+
+    let Some(details) = envelope.response.publishedfiledetails.into_iter().next() else {
+        return Ok(None);
+    };
+
+    if details.result != 1 {
+        return Ok(None);
+    }
+
+    Ok(Some(details.time_updated))
+}
+
+fn load_mods_txt(list_path: &Path) -> Result<Vec<RimMod>> {
     let file = OpenOptions::new()
         .read(true)
         .open(list_path)?;
 
     let reader = BufReader::new(file);
-    
+    let client = http_client()?;
+
     let mut mods = Vec::new();
 
     for row in reader.lines() {
@@ -198,23 +1023,154 @@ fn load_mods(list_path: &Path) -> Result<Vec<RimMod>> {
         let url = parts[0].to_owned();
         let name = parts[1..].join(" ").to_owned();
 
+        let id = extract_workshop_id(&url)?;
+
+        println!("Found mod {name} - ({url})");
+
+        for member_id in expand_collection(&client, id) {
+            mods.push(RimMod {
+                name: name.clone(),
+                id: member_id,
+                _url: url.clone(),
+                pinned: false,
+                enabled: true,
+                load_order: None,
+            });
+        }
+    }
+
+    Ok(mods)
+}
+
+fn load_mods_toml(list_path: &Path) -> Result<Vec<RimMod>> {
+    let contents = fs::read_to_string(list_path)?;
+    let manifest: ModsManifest = toml::from_str(&contents)?;
+    let client = http_client()?;
 
-        let url_split = url.split("?id=")
-            .collect::<Vec<&str>>();
+    let mut mods = Vec::new();
 
-        let id: i64 = {
-            let id_str = *url_split.get(1).expect("Mod Id");
-            id_str.parse::<i64>()?
+    for entry in manifest.mods {
+        let (id, url) = match (entry.id, entry.url) {
+            (Some(id), url) => (id, url.unwrap_or_default()),
+            (None, Some(url)) => (extract_workshop_id(&url)?, url),
+            (None, None) => bail!("Mod '{}' in mods.toml must specify an 'id' or a 'url'", entry.name),
         };
 
-        println!("Found mod {name} - ({url})");
+        println!("Found mod {} - ({})", entry.name, id);
 
-        mods.push(RimMod { 
-            name, 
-            id, 
-            _url: url
-        });
+        for member_id in expand_collection(&client, id) {
+            mods.push(RimMod {
+                name: entry.name.clone(),
+                id: member_id,
+                _url: url.clone(),
+                pinned: entry.pinned,
+                enabled: entry.enabled,
+                load_order: entry.load_order,
+            });
+        }
     }
 
     Ok(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(package_id: &str, load_after: &[&str], load_before: &[&str]) -> ModMetaData {
+        ModMetaData {
+            package_id: package_id.to_owned(),
+            mod_dependencies: ModDependencyList::default(),
+            load_after: StringList { li: load_after.iter().map(|s| s.to_string()).collect() },
+            load_before: StringList { li: load_before.iter().map(|s| s.to_string()).collect() },
+        }
+    }
+
+    #[test]
+    fn topo_sort_respects_load_after() {
+        let metas = vec![
+            meta("b.mod", &["a.mod"], &[]),
+            meta("a.mod", &[], &[]),
+        ];
+
+        let sorted = topo_sort_mods(metas, &HashMap::new());
+
+        assert_eq!(sorted, vec!["a.mod".to_owned(), "b.mod".to_owned()]);
+    }
+
+    #[test]
+    fn topo_sort_breaks_ties_with_overrides() {
+        let metas = vec![
+            meta("a.mod", &[], &[]),
+            meta("b.mod", &[], &[]),
+        ];
+        let overrides = HashMap::from([("b.mod".to_owned(), 0), ("a.mod".to_owned(), 1)]);
+
+        let sorted = topo_sort_mods(metas, &overrides);
+
+        assert_eq!(sorted, vec!["b.mod".to_owned(), "a.mod".to_owned()]);
+    }
+
+    #[test]
+    fn topo_sort_appends_cyclic_mods_unordered() {
+        let metas = vec![
+            meta("a.mod", &["b.mod"], &[]),
+            meta("b.mod", &["a.mod"], &[]),
+        ];
+
+        let sorted = topo_sort_mods(metas, &HashMap::new());
+
+        assert_eq!(sorted.len(), 2);
+        assert!(sorted.contains(&"a.mod".to_owned()));
+        assert!(sorted.contains(&"b.mod".to_owned()));
+    }
+
+    #[test]
+    fn extract_workshop_id_parses_query_param() {
+        let id = extract_workshop_id("https://steamcommunity.com/sharedfiles/filedetails/?id=123").unwrap();
+        assert_eq!(id, 123);
+    }
+
+    #[test]
+    fn extract_workshop_id_errors_without_id_param() {
+        assert!(extract_workshop_id("https://steamcommunity.com/sharedfiles/filedetails/").is_err());
+    }
+
+    #[test]
+    fn mods_manifest_parses_toml() {
+        let manifest: ModsManifest = toml::from_str(r#"
+            [[mod]]
+            name = "Harmony"
+            id = 2009463077
+            pinned = true
+        "#).unwrap();
+
+        assert_eq!(manifest.mods.len(), 1);
+        assert_eq!(manifest.mods[0].id, Some(2009463077));
+        assert!(manifest.mods[0].pinned);
+        assert!(manifest.mods[0].enabled);
+    }
+
+    #[test]
+    fn splice_active_mods_preserves_rest_of_document() {
+        let existing = "<ModsConfigData>\n  <version>1.0</version>\n  <activeMods>\n    <li>old</li>\n  </activeMods>\n  <knownExpansions>\n    <li>ludeon.rimworld.royalty</li>\n  </knownExpansions>\n</ModsConfigData>\n";
+        let block = render_active_mods_block(&["ludeon.rimworld".to_owned()]);
+
+        let result = splice_active_mods(existing, &block).unwrap();
+
+        assert!(result.contains("<knownExpansions>"));
+        assert!(result.contains("ludeon.rimworld.royalty"));
+        assert!(result.contains("ludeon.rimworld</li>"));
+    }
+
+    #[test]
+    fn splice_active_mods_handles_self_closing_tag() {
+        let existing = "<ModsConfigData>\n  <version>1.0</version>\n  <activeMods />\n  <knownExpansions />\n</ModsConfigData>\n";
+        let block = render_active_mods_block(&["ludeon.rimworld".to_owned()]);
+
+        let result = splice_active_mods(existing, &block).unwrap();
+
+        assert!(result.contains("<knownExpansions />"));
+        assert!(result.contains("ludeon.rimworld</li>"));
+    }
 }
\ No newline at end of file